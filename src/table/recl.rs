@@ -1,15 +1,45 @@
 //! EBR based garbage collector.
+//!
+//! This is hand-maintained in parallel with the `Box`/raw-pointer variant in
+//! `spec::x86_64::recl`; the two implement the same scheme but are not
+//! derived from a shared source, so a fix or invariant change made here
+//! (bag sealing, epoch advancement, shutdown/leak behavior, etc.) must be
+//! checked against that copy too.
 
 use once_cell::sync::Lazy;
 use once_cell::unsync::Lazy as UnsyncLazy;
-use std::mem::{align_of, replace, size_of};
+use std::cell::{Cell, UnsafeCell};
+use std::mem::{align_of, replace, size_of, MaybeUninit};
+use std::num::Wrapping;
 use std::ops::Deref;
 use std::ptr;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::thread;
 use std::time::Duration;
 
+/// Maximum number of deferred functions held by a single bag before it is
+/// sealed and handed off to the collector.
+///
+/// Under the `sanitize` feature this is shrunk drastically (mirroring
+/// crossbeam-epoch) so that bags seal, and so become eligible for collection,
+/// far more often. A premature-free bug only surfaces when the epoch advances at just
+/// the wrong moment, which is rare with large batches; running the test suite
+/// under `sanitize` (ideally with Miri or TSan) makes that window much wider.
+#[cfg(not(feature = "sanitize"))]
+const MAX_OBJECTS: usize = 62;
+#[cfg(feature = "sanitize")]
+const MAX_OBJECTS: usize = 4;
+
+/// Default number of pins between a participant's self-triggered attempts to
+/// advance the global epoch. See [`Collector::with_epoch_advance_interval`].
+///
+/// Under `sanitize` this is 1, so every single pin attempts an advance.
+#[cfg(not(feature = "sanitize"))]
+const DEFAULT_EPOCH_ADVANCE_INTERVAL: usize = 128;
+#[cfg(feature = "sanitize")]
+const DEFAULT_EPOCH_ADVANCE_INTERVAL: usize = 1;
+
 static GUARDIAN_SLEEP_DURATION: Duration = Duration::from_millis(100);
 
 pub fn enter_critical() {
@@ -24,53 +54,212 @@ pub fn exit_critical() {
     });
 }
 
+/// Pin the current thread, returning a [`Guard`] that keeps it pinned until dropped.
+///
+/// Unlike [`protected`], the returned guard isn't tied to a single closure: it can be
+/// held across loops, early returns and other non-lexical scopes.
+pub fn pin() -> Guard {
+    PARTICIPANT_HANDLE.with(|key| Guard::new(&***key as *const Local))
+}
+
 /// Execute a closure in protected mode. This permits it to load protected pointers.
 pub fn protected<T>(f: impl FnOnce() -> T) -> T {
-    PARTICIPANT_HANDLE.with(|key| {
-        key.enter_critical();
-        let r = f();
-        key.exit_critical();
-        r
-    })
+    let guard = pin();
+    let r = f();
+    drop(guard);
+    r
 }
 
 /// Defer a function.
 pub fn defer(f: impl FnOnce()) {
-    let deferred = Deferred::new(f);
-    PARTICIPANT_HANDLE.with(|key| key.defer(deferred));
+    PARTICIPANT_HANDLE.with(|key| key.defer(f));
 }
 
-fn guardian_thread_fn(gc: Arc<Global>) {
+/// Force the current thread's pending deferreds to become visible to the
+/// collector and attempt an immediate collection, rather than waiting for its
+/// bag to fill or the guardian thread's next tick.
+pub fn flush() {
+    PARTICIPANT_HANDLE.with(|key| key.flush());
+}
+
+fn guardian_thread_fn(global: Weak<Global>, interval: Duration) {
     loop {
-        thread::sleep(GUARDIAN_SLEEP_DURATION);
-        gc.collect();
+        thread::sleep(interval);
+        match global.upgrade() {
+            Some(global) => global.collect(),
+            None => return,
+        }
     }
 }
 
-static GC: Lazy<Arc<Global>> = Lazy::new(|| {
-    let state = Arc::new(Global::new());
-    let state2 = Arc::clone(&state);
-    thread::spawn(|| guardian_thread_fn(state2));
-    state
-});
+// The default, process-wide collector backing the free functions above. Kept around
+// for callers that don't need isolated reclamation and just want the old behavior.
+static GC: Lazy<Collector> = Lazy::new(Collector::new);
 
 thread_local! {
-    pub static PARTICIPANT_HANDLE: UnsyncLazy<TSLocal> = UnsyncLazy::new(|| TSLocal::new(Arc::clone(&GC)));
+    pub static PARTICIPANT_HANDLE: UnsyncLazy<LocalHandle> = UnsyncLazy::new(|| GC.register());
+}
+
+/// A cloneable handle to a garbage collector.
+///
+/// A `Collector` owns the participant registry and global epoch that its
+/// registered [`LocalHandle`]s defer reclamation through. Each `DashMap` can
+/// own its own `Collector` so that its deferred destructors are torn down
+/// deterministically when the map is dropped, rather than leaking a
+/// participant registration into a shared global list for the life of the
+/// process. Cloning a `Collector` yields another handle to the *same*
+/// underlying state; call [`Collector::new`] to get an independent one.
+#[derive(Clone)]
+pub struct Collector {
+    global: Arc<Global>,
+}
+
+impl Collector {
+    /// Create a new collector with its own guardian thread, ticking every
+    /// [`GUARDIAN_SLEEP_DURATION`]. The guardian thread holds only a weak
+    /// reference and exits on its own once every handle to this collector has
+    /// been dropped.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Like [`Collector::new`], but participants attempt a global epoch
+    /// advance every `n` pins instead of the default of
+    /// [`DEFAULT_EPOCH_ADVANCE_INTERVAL`]. This lets hot, mostly-pinned
+    /// workloads self-collect instead of waiting on the guardian thread.
+    pub fn with_epoch_advance_interval(n: usize) -> Self {
+        Self::builder().epoch_advance_interval(n).build()
+    }
+
+    /// Start building a collector with non-default configuration. See
+    /// [`CollectorBuilder`].
+    pub fn builder() -> CollectorBuilder {
+        CollectorBuilder::new()
+    }
+
+    /// Register a new participant with this collector.
+    pub fn register(&self) -> LocalHandle {
+        let local = Arc::new(Local::new(Arc::clone(&self.global)));
+        self.global.add_local(Arc::clone(&local));
+        LocalHandle { local }
+    }
+
+    /// Force this collector to attempt an immediate epoch advance and
+    /// collection, rather than waiting for the guardian thread's next tick.
+    /// Note that a thread's own pending deferreds are only visible to the
+    /// collector once sealed into a bag; see [`LocalHandle::flush`] to also
+    /// seal that thread's partially-filled bag first.
+    pub fn collect(&self) {
+        self.global.collect();
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub struct TSLocal {
+/// Builds a [`Collector`] with non-default configuration: how many pins
+/// between self-triggered epoch advances, and how (or whether) its guardian
+/// thread runs in the background.
+pub struct CollectorBuilder {
+    epoch_advance_interval: usize,
+    guardian: GuardianMode,
+}
+
+/// How a [`Collector`]'s guardian thread, if any, advances the epoch in the
+/// background.
+#[derive(Clone, Copy)]
+enum GuardianMode {
+    /// Spawn a guardian thread that attempts a collection every `Duration`.
+    Automatic(Duration),
+    /// Spawn no guardian thread; progress is only made via pin-counter-driven
+    /// advancement and explicit calls to [`flush`] or [`Collector::collect`].
+    Manual,
+}
+
+impl CollectorBuilder {
+    fn new() -> Self {
+        Self {
+            epoch_advance_interval: DEFAULT_EPOCH_ADVANCE_INTERVAL,
+            guardian: GuardianMode::Automatic(GUARDIAN_SLEEP_DURATION),
+        }
+    }
+
+    /// Participants attempt a global epoch advance every `n` pins. See
+    /// [`Collector::with_epoch_advance_interval`].
+    pub fn epoch_advance_interval(mut self, n: usize) -> Self {
+        assert!(n > 0, "epoch_advance_interval must be greater than zero");
+        self.epoch_advance_interval = n;
+        self
+    }
+
+    /// Run the guardian thread on `interval` instead of the default
+    /// [`GUARDIAN_SLEEP_DURATION`].
+    pub fn guardian_interval(mut self, interval: Duration) -> Self {
+        self.guardian = GuardianMode::Automatic(interval);
+        self
+    }
+
+    /// Spawn no guardian thread at all. This suits embedded or
+    /// single-threaded-runtime users who don't want a hidden background
+    /// thread, and deterministic tests: progress is then made only through
+    /// pin-counter-driven advancement and explicit calls to [`flush`] or
+    /// [`Collector::collect`].
+    pub fn no_guardian(mut self) -> Self {
+        self.guardian = GuardianMode::Manual;
+        self
+    }
+
+    /// Build the configured collector.
+    pub fn build(self) -> Collector {
+        let global = Arc::new(Global::new(self.epoch_advance_interval));
+        if let GuardianMode::Automatic(interval) = self.guardian {
+            thread::spawn({
+                let global = Arc::downgrade(&global);
+                move || guardian_thread_fn(global, interval)
+            });
+        }
+        Collector { global }
+    }
+}
+
+/// A handle to a thread's participation in a [`Collector`]'s reclamation scheme.
+pub struct LocalHandle {
     local: Arc<Local>,
 }
 
-impl TSLocal {
-    fn new(global: Arc<Global>) -> TSLocal {
-        let local = Arc::new(Local::new(Arc::clone(&global)));
-        global.add_local(local.clone());
-        Self { local }
+impl LocalHandle {
+    pub fn enter_critical(&self) {
+        self.local.enter_critical();
+    }
+
+    pub fn exit_critical(&self) {
+        self.local.exit_critical();
+    }
+
+    pub fn defer(&self, f: impl FnOnce()) {
+        self.local.defer(Deferred::new(f));
+    }
+
+    /// Pin this handle's participant, returning a [`Guard`] that keeps it
+    /// pinned until dropped.
+    pub fn pin(&self) -> Guard {
+        Guard::new(&*self.local as *const Local)
+    }
+
+    /// Seal this participant's partially-filled bag (making its pending
+    /// deferreds visible to the collector) and attempt an immediate
+    /// collection, rather than waiting for the bag to fill or the guardian
+    /// thread's next tick.
+    pub fn flush(&self) {
+        self.local.seal_current();
+        self.local.global.collect();
     }
 }
 
-impl Deref for TSLocal {
+impl Deref for LocalHandle {
     type Target = Local;
 
     fn deref(&self) -> &Self::Target {
@@ -78,9 +267,83 @@ impl Deref for TSLocal {
     }
 }
 
+impl Drop for LocalHandle {
+    fn drop(&mut self) {
+        self.local.global.mark_deleted(&self.local);
+
+        // Eagerly attempt to unlink and free this (now-deleted) participant
+        // rather than leaving it for a future guardian tick or pin-counter
+        // advance that may never come (e.g. under
+        // `CollectorBuilder::no_guardian`). This is what lets the collector's
+        // `sealed` bags and this participant's own `current` bag actually run
+        // their pending deferreds once every handle and the `Collector` are
+        // dropped: `Global` carries no custom `Drop` impl, so its fields
+        // (including any still-sealed bags) are freed as soon as the last
+        // `Arc<Global>` clone goes away, which can only happen once every
+        // registered `Local` has been unlinked here.
+        self.local.global.collect();
+    }
+}
+
+/// An RAII guard for a pinned participant.
+///
+/// A `Guard` enters its participant's critical section on construction and
+/// exits it on drop, replacing manually-paired `enter_critical`/`exit_critical`
+/// calls (which are easy to mis-pair across non-lexical scopes). Loading of
+/// protected pointers should require a `&Guard` as a witness that the current
+/// thread is pinned. Reclamation can be scheduled through the guard itself via
+/// [`Guard::defer`].
+pub struct Guard {
+    local: *const Local,
+}
+
+impl Guard {
+    fn new(local: *const Local) -> Self {
+        // # Safety: `local` outlives this guard; see the callers of `Guard::new`.
+        unsafe { (*local).enter_critical() };
+        Self { local }
+    }
+
+    /// Defer a function to run once no guard can observe the objects it frees.
+    pub fn defer(&self, f: impl FnOnce()) {
+        // # Safety: see the comment in `Guard::new`.
+        unsafe { (*self.local).defer(Deferred::new(f)) };
+    }
+
+    /// Exit and immediately re-enter the critical section, letting the epoch
+    /// advance. Useful for long scans that shouldn't pin a single epoch
+    /// indefinitely.
+    pub fn repin(&mut self) {
+        // # Safety: see the comment in `Guard::new`.
+        unsafe {
+            (*self.local).exit_critical();
+            (*self.local).enter_critical();
+        }
+    }
+
+    /// Like [`Guard::repin`], but additionally runs `f` while unpinned before
+    /// re-entering the critical section.
+    pub fn repin_after<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        // # Safety: see the comment in `Guard::new`.
+        unsafe { (*self.local).exit_critical() };
+        let r = f();
+        unsafe { (*self.local).enter_critical() };
+        r
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // # Safety: see the comment in `Guard::new`.
+        unsafe { (*self.local).exit_critical() };
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Deferred;
+    use super::{Collector, Deferred};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn defer_external() {
@@ -88,6 +351,143 @@ mod tests {
         let deferred = Deferred::new(|| println!("{:?}", a));
         deferred.run();
     }
+
+    /// Regression test: under `no_guardian`, a deregistered participant's
+    /// pending deferreds must still run once every `LocalHandle` and the
+    /// `Collector` itself are dropped, even with no background guardian
+    /// thread and no further explicit `flush`/`collect` calls.
+    #[test]
+    fn no_guardian_runs_deferred_after_last_handle_and_collector_drop() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let collector = Collector::builder().no_guardian().build();
+        let handle = collector.register();
+
+        let ran_handle = Arc::clone(&ran);
+        handle.defer(move || ran_handle.store(true, Ordering::SeqCst));
+        handle.flush();
+
+        drop(handle);
+        drop(collector);
+
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    /// Regression test: with a short `epoch_advance_interval` and no
+    /// guardian, pinning alone (no `flush` or explicit `collect` call) should
+    /// drive the epoch forward far enough to run a closure sealed into a bag
+    /// by ordinary `defer` overflow.
+    #[test]
+    fn pin_counter_alone_advances_epoch_without_guardian() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let collector = Collector::builder()
+            .no_guardian()
+            .epoch_advance_interval(1)
+            .build();
+        let handle = collector.register();
+
+        let ran_handle = Arc::clone(&ran);
+        handle.defer(move || ran_handle.store(true, Ordering::SeqCst));
+        // Seal that bag by filling it, without ever calling `flush`.
+        for _ in 0..super::MAX_OBJECTS {
+            handle.defer(|| {});
+        }
+
+        for _ in 0..8 {
+            drop(handle.pin());
+        }
+
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[should_panic(expected = "epoch_advance_interval must be greater than zero")]
+    fn epoch_advance_interval_rejects_zero() {
+        Collector::builder().epoch_advance_interval(0);
+    }
+
+    /// Under `sanitize`, bags seal far more often (`MAX_OBJECTS = 4`) and
+    /// `collect` advances as aggressively as possible; this exercises many
+    /// more seal/collect cycles than the non-sanitize tests above and checks
+    /// that every deferred closure still runs exactly once, without tripping
+    /// the `sealed_generation` assertion in `Global::collect`.
+    #[cfg(feature = "sanitize")]
+    #[test]
+    fn sanitize_mode_runs_every_deferred_exactly_once() {
+        use std::sync::atomic::AtomicUsize;
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        let collector = Collector::builder().no_guardian().build();
+        let handle = collector.register();
+
+        for _ in 0..16 {
+            let ran = Arc::clone(&ran);
+            handle.defer(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+            handle.flush();
+        }
+
+        drop(handle);
+        drop(collector);
+
+        assert_eq!(ran.load(Ordering::SeqCst), 16);
+    }
+
+    /// Stress the lock-free participant list itself (added for `sanitize`):
+    /// many threads concurrently register, defer, and deregister while a
+    /// separate thread concurrently forces `collect()`, contending on the
+    /// same `prev_link` CAS in `Global::collect`'s unlink loop. Regression
+    /// test for a bug where a failed unlink CAS cleared the whole `retired`
+    /// batch on retry, losing already-unlinked participants (and the
+    /// deferreds they still owned) for good.
+    #[cfg(feature = "sanitize")]
+    #[test]
+    fn concurrent_register_deregister_and_collect_run_every_deferred_exactly_once() {
+        use std::sync::atomic::AtomicUsize;
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        let collector = Collector::builder().no_guardian().build();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let collector_thread = {
+            let collector = collector.clone();
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    collector.collect();
+                }
+            })
+        };
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let collector = collector.clone();
+                let ran = Arc::clone(&ran);
+                std::thread::spawn(move || {
+                    for _ in 0..64 {
+                        let handle = collector.register();
+                        let ran = Arc::clone(&ran);
+                        handle.defer(move || {
+                            ran.fetch_add(1, Ordering::SeqCst);
+                        });
+                        handle.flush();
+                        drop(handle);
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        collector_thread.join().unwrap();
+
+        drop(collector);
+
+        assert_eq!(ran.load(Ordering::SeqCst), 8 * 64);
+    }
 }
 
 struct Deferred {
@@ -151,12 +551,108 @@ impl Deferred {
     }
 }
 
+/// A fixed-capacity, lock-free batch of deferred functions.
+///
+/// Filling a bag (via [`Bag::try_push`]) never takes a lock: it's only ever
+/// touched by the thread that owns the `Local` it belongs to. Once full, a
+/// bag is sealed, tagged with the epoch it was sealed in, and handed off to
+/// the collector's global queue, so synchronization only happens once per
+/// [`MAX_OBJECTS`] deferred functions rather than on every single one.
+struct Bag {
+    deferreds: [MaybeUninit<Deferred>; MAX_OBJECTS],
+    len: usize,
+
+    // Generation this bag was sealed in (see `Global::generation`). Only
+    // tracked under `sanitize`, to assert that at least three epoch advances
+    // have happened when the bag is eventually run.
+    #[cfg(feature = "sanitize")]
+    sealed_generation: usize,
+}
+
+impl Bag {
+    fn new() -> Self {
+        Self {
+            // # Safety
+            // An array of `MaybeUninit` is valid in its uninitialized state.
+            deferreds: unsafe { MaybeUninit::uninit().assume_init() },
+            len: 0,
+            #[cfg(feature = "sanitize")]
+            sealed_generation: 0,
+        }
+    }
+
+    /// Try to stash `deferred` in this bag, returning it back if the bag is full.
+    fn try_push(&mut self, deferred: Deferred) -> Result<(), Deferred> {
+        if self.len == MAX_OBJECTS {
+            return Err(deferred);
+        }
+        self.deferreds[self.len] = MaybeUninit::new(deferred);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Run and drop every deferred function currently stashed in this bag.
+    fn run_all(&mut self) {
+        for slot in &mut self.deferreds[..self.len] {
+            // # Safety
+            // Every slot below `self.len` was initialized by `try_push`.
+            unsafe { slot.assume_init_read() }.run();
+        }
+        self.len = 0;
+    }
+}
+
+impl Drop for Bag {
+    fn drop(&mut self) {
+        self.run_all();
+    }
+}
+
+/// An intrusive node in `Global`'s lock-free participant list.
+///
+/// `next` stores a tagged pointer to the next `Local` in the list: the lowest
+/// bit marks *this* entry as logically deleted (its participant deregistered,
+/// but it may not yet be physically unlinked). Registration CAS-prepends a
+/// node onto the list; deregistration just sets the tag. Physical unlinking
+/// (and releasing the list's strong reference) happens lazily the next time
+/// `Global::collect` traverses past a deleted entry.
+struct Entry {
+    next: AtomicUsize,
+}
+
+/// Tag bit marking an `Entry` as logically deleted.
+const DELETED: usize = 1;
+
 struct Global {
     // Global epoch. This value is always 0, 1 or 2.
     epoch: AtomicUsize,
 
-    // List of participants.
-    locals: Mutex<Vec<Arc<Local>>>,
+    // Head of the intrusive, lock-free participant list: a tagged pointer (see
+    // `Entry`) to the first `Local`, or 0 if the list is empty. The list owns
+    // one `Arc<Local>` strong reference per linked node.
+    head: AtomicUsize,
+
+    // Number of `collect` traversals of the participant list currently in
+    // flight. A node unlinked during a traversal is only released once this
+    // drops to zero, so a concurrent traversal can never read freed memory.
+    readers: AtomicUsize,
+
+    // Sealed bags, queued by the epoch in which they were sealed. A bag in
+    // bucket `e` becomes safe to collect once the global epoch has advanced
+    // far enough that no participant can still be observing epoch `e`.
+    sealed: Mutex<[Vec<Bag>; 3]>,
+
+    // Number of pins between a participant's self-triggered attempts to
+    // advance the global epoch.
+    epoch_advance_interval: usize,
+
+    // Monotonic count of epoch advances, distinct from `epoch` itself (which
+    // wraps mod 3). Only tracked under `sanitize`, to assert that a bag is
+    // never run until at least three epoch advances have happened since it
+    // was sealed (the three-bucket `sealed` scheme only drains a bucket once
+    // the epoch cycles all the way back to the value it had at seal time).
+    #[cfg(feature = "sanitize")]
+    generation: AtomicUsize,
 }
 
 fn increment_epoch(a: &AtomicUsize) -> usize {
@@ -170,48 +666,167 @@ fn increment_epoch(a: &AtomicUsize) -> usize {
 }
 
 impl Global {
-    fn new() -> Self {
+    fn new(epoch_advance_interval: usize) -> Self {
         Self {
             epoch: AtomicUsize::new(0),
-            locals: Mutex::new(Vec::new()),
+            head: AtomicUsize::new(0),
+            readers: AtomicUsize::new(0),
+            sealed: Mutex::new([Vec::new(), Vec::new(), Vec::new()]),
+            epoch_advance_interval,
+            #[cfg(feature = "sanitize")]
+            generation: AtomicUsize::new(0),
         }
     }
 
+    /// Register `local` with this collector, handing the list one strong
+    /// reference (the registrant keeps its own).
     fn add_local(&self, local: Arc<Local>) {
-        self.locals.lock().unwrap().push(local);
+        let ptr = Arc::into_raw(local);
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            // # Safety: `ptr` was just produced by `Arc::into_raw` above.
+            unsafe { &*ptr }.entry.next.store(head, Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange_weak(head, ptr as usize, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
+            }
+        }
     }
 
-    fn collect(&self) {
-        PARTICIPANT_HANDLE.with(|key| {
-            UnsyncLazy::force(key);
-        });
-
-        let start_global_epoch = self.epoch.load(Ordering::Acquire);
-        let mut locals = self.locals.lock().unwrap();
-        let mut local_lists = Vec::new();
-        for local_ptr in &*locals {
-            let local = &**local_ptr;
-            local_lists.push(&local.deferred);
-            if local.active.load(Ordering::Acquire) > 0
-                && local.epoch.load(Ordering::Acquire) != start_global_epoch
+    /// Mark `local` as deregistered. It stays in the list (and keeps its
+    /// strong reference alive) until the next `collect` traversal unlinks it.
+    fn mark_deleted(&self, local: &Local) {
+        loop {
+            let next = local.entry.next.load(Ordering::Acquire);
+            if next & DELETED != 0 {
+                return;
+            }
+            if local
+                .entry
+                .next
+                .compare_exchange_weak(next, next | DELETED, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
             {
                 return;
             }
         }
-        if start_global_epoch != self.epoch.load(Ordering::Acquire) {
-            return;
+    }
+
+    /// Queue a bag of deferreds sealed by one of this collector's participants.
+    ///
+    /// Reads the current epoch (and, under `sanitize`, stamps the bag's
+    /// generation) only after taking `self.sealed`'s lock, which
+    /// `Global::collect` also holds for the duration of its own epoch
+    /// advance: without that, a bag could be routed to whatever bucket the
+    /// epoch happened to be at when this was *called*, but by the time it
+    /// was actually pushed a concurrent `collect()` could already be
+    /// draining that exact bucket, running the bag far sooner than the
+    /// three-advance quarantine it's supposed to get.
+    fn push_sealed(&self, #[allow(unused_mut)] mut bag: Bag) {
+        let mut sealed = self.sealed.lock().unwrap();
+        let epoch = self.epoch.load(Ordering::Acquire);
+        #[cfg(feature = "sanitize")]
+        {
+            bag.sealed_generation = self.generation.load(Ordering::Acquire);
         }
-        let next = increment_epoch(&self.epoch);
-        for local_deferred_l in local_lists {
-            let mut local_deferred = local_deferred_l.lock().unwrap();
-            let to_collect = replace(&mut local_deferred[next], Vec::new());
-            drop(local_deferred);
-            for deferred in to_collect {
-                deferred.run();
+        sealed[epoch].push(bag);
+    }
+
+    fn collect(&self) {
+        self.readers.fetch_add(1, Ordering::AcqRel);
+
+        let start_global_epoch = self.epoch.load(Ordering::Acquire);
+        let mut retired: Vec<*const Local> = Vec::new();
+        let mut blocked = false;
+
+        'scan: loop {
+            let mut prev_link: *const AtomicUsize = &self.head;
+            let mut curr = self.head.load(Ordering::Acquire);
+
+            while curr != 0 {
+                let curr_local = curr as *const Local;
+                // # Safety: `curr_local` is kept alive by the list's own
+                // strong reference until it's unlinked below; unlinking can't
+                // race a concurrent free because of `self.readers`.
+                let next = unsafe { &*curr_local }.entry.next.load(Ordering::Acquire);
+
+                if next & DELETED != 0 {
+                    let next_ptr = next & !DELETED;
+                    let unlinked = unsafe { &*prev_link }
+                        .compare_exchange(curr, next_ptr, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok();
+                    if unlinked {
+                        retired.push(curr_local);
+                        curr = next_ptr;
+                        continue;
+                    }
+                    // Lost a race with a concurrent registration/unlink; restart the
+                    // scan. Nodes already unlinked earlier in this pass are gone from
+                    // the list and can't be re-encountered, so keep accumulating into
+                    // `retired` rather than clearing it — clearing here would leak
+                    // those nodes (and any deferreds they still owned) for good.
+                    blocked = false;
+                    continue 'scan;
+                }
+
+                let local = unsafe { &*curr_local };
+                if local.active.load(Ordering::Acquire) > 0
+                    && local.epoch.load(Ordering::Acquire) != start_global_epoch
+                {
+                    blocked = true;
+                }
+
+                prev_link = &local.entry.next;
+                curr = next;
             }
+
+            break;
+        }
+
+        let ready = if blocked || start_global_epoch != self.epoch.load(Ordering::Acquire) {
+            None
+        } else {
+            // Advance and drain under `self.sealed`'s lock, the same one
+            // `push_sealed` holds while routing a bag to a bucket — see its
+            // doc comment for why that pairing matters.
+            let mut sealed = self.sealed.lock().unwrap();
+            let next = increment_epoch(&self.epoch);
+            #[cfg(feature = "sanitize")]
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            Some(replace(&mut sealed[next], Vec::new()))
+        };
+
+        // Wait for any other concurrent traversal to finish before releasing
+        // the nodes just unlinked above: a raw pointer read by another
+        // traversal must not outlive the memory it points to.
+        self.readers.fetch_sub(1, Ordering::AcqRel);
+        while self.readers.load(Ordering::Acquire) > 0 {
+            std::hint::spin_loop();
+        }
+        for local in retired {
+            // # Safety: `local` was registered via `Arc::into_raw` in
+            // `add_local` and has just been unlinked, and no other traversal
+            // can still be observing it (see the wait above).
+            drop(unsafe { Arc::from_raw(local) });
         }
 
-        locals.retain(|arc| Arc::strong_count(arc) > 1)
+        if let Some(ready) = ready {
+            for mut bag in ready {
+                #[cfg(feature = "sanitize")]
+                {
+                    let current_generation = self.generation.load(Ordering::Acquire);
+                    assert!(
+                        current_generation >= bag.sealed_generation + 3,
+                        "bag run after only {} epoch advance(s) since it was sealed",
+                        current_generation - bag.sealed_generation,
+                    );
+                }
+                bag.run_all();
+            }
+        }
     }
 }
 
@@ -225,29 +840,39 @@ pub struct Local {
     // Reference to global state.
     global: Arc<Global>,
 
-    // Objects marked for deletion.
-    deferred: Mutex<[Vec<Deferred>; 3]>,
-}
+    // The bag this participant is currently filling. Only ever touched by the
+    // thread that owns this `Local`: `defer` is the sole writer, and once a
+    // bag fills it is sealed off into `Global::sealed` for the collector to
+    // pick up, so no lock is needed here.
+    current: UnsafeCell<Bag>,
 
-impl Drop for Local {
-    fn drop(&mut self) {
-        let mut deferred = self.deferred.lock().unwrap();
+    // Number of times this participant has pinned, wrapping on overflow. Used
+    // to periodically attempt a global epoch advance without waiting on the
+    // guardian thread; only ever touched by the owning thread.
+    pin_count: Cell<Wrapping<usize>>,
 
-        for i in 0..3 {
-            for deferred in replace(&mut deferred[i], Vec::new()) {
-                deferred.run();
-            }
-        }
-    }
+    // This participant's node in the collector's intrusive participant list.
+    entry: Entry,
 }
 
+// # Safety
+// `current` is only ever accessed by the thread that owns this `Local`. The
+// collector (running on a different thread) only ever reads `active` and
+// `epoch`, both of which are atomics, so sharing `Local` behind an `Arc`
+// across threads is sound.
+unsafe impl Sync for Local {}
+
 impl Local {
     fn new(global: Arc<Global>) -> Self {
         Self {
             active: AtomicUsize::new(0),
             epoch: AtomicUsize::new(0),
             global,
-            deferred: Mutex::new([Vec::new(), Vec::new(), Vec::new()]),
+            current: UnsafeCell::new(Bag::new()),
+            pin_count: Cell::new(Wrapping(0)),
+            entry: Entry {
+                next: AtomicUsize::new(0),
+            },
         }
     }
 
@@ -256,6 +881,12 @@ impl Local {
             let global_epoch = self.global.epoch.load(Ordering::Relaxed);
             self.epoch.store(global_epoch, Ordering::Relaxed);
         }
+
+        let count = self.pin_count.get() + Wrapping(1);
+        self.pin_count.set(count);
+        if count.0 % self.global.epoch_advance_interval == 0 {
+            self.global.collect();
+        }
     }
 
     fn exit_critical(&self) {
@@ -263,12 +894,26 @@ impl Local {
     }
 
     fn defer(&self, f: Deferred) {
-        let global_epoch = self.global.epoch.load(Ordering::Relaxed);
-        let mut deferred = self
-            .deferred
-            .lock()
-            .unwrap_or_else(|_| std::process::abort());
+        // # Safety: see the comment on `current` above.
+        let current = unsafe { &mut *self.current.get() };
+
+        if let Err(f) = current.try_push(f) {
+            let sealed = replace(current, Bag::new());
+            self.global.push_sealed(sealed);
 
-        deferred[global_epoch].push(f);
+            // The fresh bag is empty, so this can't fail.
+            current.try_push(f).unwrap_or_else(|_| unreachable!());
+        }
     }
-}
\ No newline at end of file
+
+    /// Seal this participant's current bag, if non-empty, handing it to the
+    /// collector immediately instead of waiting for it to fill.
+    fn seal_current(&self) {
+        // # Safety: see the comment on `current` above.
+        let current = unsafe { &mut *self.current.get() };
+        if current.len > 0 {
+            let sealed = replace(current, Bag::new());
+            self.global.push_sealed(sealed);
+        }
+    }
+}